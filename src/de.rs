@@ -0,0 +1,351 @@
+//! A `serde::Deserializer` built directly on top of `Decoder`'s offset
+//! machinery, so structural errors keep the same `offset` a caller would
+//! get from `decode`.
+
+use serde::de::{self, Deserialize, DeserializeSeed, EnumAccess, Error as DeError, MapAccess, SeqAccess, Visitor, VariantAccess};
+use std::fmt;
+use std::io::Cursor;
+
+use crate::{DecodeError, DictEntry, Element};
+
+#[derive(Debug)]
+pub enum Error {
+    Decode(DecodeError),
+    Message(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Error::Decode(e) => write!(f, "{}", e),
+	    Error::Message(msg) => write!(f, "{}", msg),
+	}
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+	Error::Message(msg.to_string())
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Deserializes a `T` from a complete bencode document.
+pub fn from_bytes<'a, T: Deserialize<'a>>(data: &'a [u8]) -> Result<T> {
+    T::deserialize(&mut Deserializer{ decoder: crate::Decoder::new(Cursor::new(data)) })
+}
+
+pub struct Deserializer<'de> {
+    decoder: crate::Decoder<Cursor<&'de [u8]>>,
+}
+
+impl<'de> Deserializer<'de> {
+    fn parse_element(&mut self) -> Result<Element> {
+	match self.decoder.next_element() {
+	    Some(Ok(element)) => Ok(element),
+	    Some(Err(e)) => Err(Error::Decode(e)),
+	    None => Err(Error::custom("unexpected end of bencode input")),
+	}
+    }
+}
+
+fn visit_element<'de, V: Visitor<'de>>(element: Element, visitor: V) -> Result<V::Value> {
+    match element {
+	Element::ByteString(bytes) => {
+	    match String::from_utf8(bytes) {
+		Ok(s) => visitor.visit_string(s),
+		Err(e) => visitor.visit_byte_buf(e.into_bytes()),
+	    }
+	},
+
+	Element::Integer(i) => visitor.visit_i64(i),
+	Element::List(list) => visitor.visit_seq(SeqWalker{ iter: list.into_iter() }),
+	Element::Dict(dict) => visitor.visit_map(MapWalker{ iter: dict.into_iter(), value: None }),
+    }
+}
+
+macro_rules! forward_to_parsed_element {
+    ($($method:ident),* $(,)?) => {
+	$(
+	    fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+		self.deserialize_any(visitor)
+	    }
+	)*
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+	let element = self.parse_element()?;
+	visit_element(element, visitor)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+	match self.parse_element()? {
+	    Element::Integer(0) => visitor.visit_bool(false),
+	    Element::Integer(1) => visitor.visit_bool(true),
+	    _ => Err(Error::custom("expected a bencode integer 0 or 1 for a bool")),
+	}
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+	// bencode has no null; a present field is always Some
+	visitor.visit_some(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+	self, _name: &'static str, _variants: &'static [&'static str], visitor: V,
+    ) -> Result<V::Value> {
+	visitor.visit_enum(EnumWalker{ element: self.parse_element()? })
+    }
+
+    forward_to_parsed_element!(
+	deserialize_i8, deserialize_i16, deserialize_i32, deserialize_i64,
+	deserialize_u8, deserialize_u16, deserialize_u32, deserialize_u64,
+	deserialize_f32, deserialize_f64,
+	deserialize_char, deserialize_str, deserialize_string,
+	deserialize_bytes, deserialize_byte_buf,
+	deserialize_unit, deserialize_seq, deserialize_map,
+	deserialize_identifier, deserialize_ignored_any,
+    );
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+	self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+	visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+	self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value> {
+	self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+	self, _name: &'static str, _fields: &'static [&'static str], visitor: V,
+    ) -> Result<V::Value> {
+	self.deserialize_map(visitor)
+    }
+}
+
+struct ElementDeserializer(Element);
+
+impl<'de> de::Deserializer<'de> for ElementDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+	visit_element(self.0, visitor)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+	match self.0 {
+	    Element::Integer(0) => visitor.visit_bool(false),
+	    Element::Integer(1) => visitor.visit_bool(true),
+	    _ => Err(Error::custom("expected a bencode integer 0 or 1 for a bool")),
+	}
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+	visitor.visit_some(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+	self, _name: &'static str, _variants: &'static [&'static str], visitor: V,
+    ) -> Result<V::Value> {
+	visitor.visit_enum(EnumWalker{ element: self.0 })
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+	visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+	i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string
+	bytes byte_buf unit unit_struct seq tuple tuple_struct
+	map struct identifier ignored_any
+    }
+}
+
+struct SeqWalker {
+    iter: std::vec::IntoIter<Element>,
+}
+
+impl<'de> SeqAccess<'de> for SeqWalker {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+	match self.iter.next() {
+	    Some(element) => seed.deserialize(ElementDeserializer(element)).map(Some),
+	    None => Ok(None),
+	}
+    }
+}
+
+struct MapWalker {
+    iter: std::vec::IntoIter<DictEntry>,
+    value: Option<Element>,
+}
+
+impl<'de> MapAccess<'de> for MapWalker {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+	match self.iter.next() {
+	    Some(entry) => {
+		self.value = Some(entry.value);
+		seed.deserialize(ElementDeserializer(Element::ByteString(entry.key))).map(Some)
+	    },
+
+	    None => Ok(None),
+	}
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+	let value = self.value.take().expect("next_value_seed called before next_key_seed");
+	seed.deserialize(ElementDeserializer(value))
+    }
+}
+
+struct EnumWalker {
+    element: Element,
+}
+
+impl<'de> EnumAccess<'de> for EnumWalker {
+    type Error = Error;
+    type Variant = VariantWalker;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant)> {
+	match self.element {
+	    Element::ByteString(name) => {
+		let value = seed.deserialize(ElementDeserializer(Element::ByteString(name)))?;
+		Ok((value, VariantWalker{ value: None }))
+	    },
+
+	    Element::Dict(mut entries) if entries.len() == 1 => {
+		let entry = entries.remove(0);
+		let value = seed.deserialize(ElementDeserializer(Element::ByteString(entry.key)))?;
+		Ok((value, VariantWalker{ value: Some(entry.value) }))
+	    },
+
+	    _ => Err(Error::custom("expected a bytestring or single-entry dict for an enum")),
+	}
+    }
+}
+
+struct VariantWalker {
+    value: Option<Element>,
+}
+
+impl<'de> VariantAccess<'de> for VariantWalker {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+	Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+	match self.value {
+	    Some(value) => seed.deserialize(ElementDeserializer(value)),
+	    None => Err(Error::custom("expected a value for a newtype variant")),
+	}
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+	match self.value {
+	    Some(Element::List(list)) => visitor.visit_seq(SeqWalker{ iter: list.into_iter() }),
+	    _ => Err(Error::custom("expected a list for a tuple variant")),
+	}
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+	match self.value {
+	    Some(Element::Dict(dict)) => visitor.visit_map(MapWalker{ iter: dict.into_iter(), value: None }),
+	    _ => Err(Error::custom("expected a dict for a struct variant")),
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Info {
+	name: String,
+	length: i64,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct TorrentFile {
+	announce: String,
+	info: Info,
+    }
+
+    #[test]
+    fn deserializes_struct_from_dict() {
+	let input: &[u8] = b"d8:announce7:tracker4:infod6:lengthi10e4:name1:aee";
+
+	assert_eq!(
+	    from_bytes::<TorrentFile>(input).unwrap(),
+	    TorrentFile{
+		announce: "tracker".to_string(),
+		info: Info{ name: "a".to_string(), length: 10 },
+	    }
+	);
+    }
+
+    #[test]
+    fn reports_offset_on_malformed_input() {
+	match from_bytes::<TorrentFile>(b"d8:announce") {
+	    Err(Error::Decode(e)) => assert_eq!(e.offset, 11),
+	    other => panic!("expected a Decode error, got {:?}", other.is_ok()),
+	}
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Message {
+	Ping,
+	Text(String),
+	Point(i64, i64),
+	Login{ name: String, id: i64 },
+    }
+
+    #[test]
+    fn deserializes_unit_variant_from_bytestring() {
+	assert_eq!(from_bytes::<Message>(b"4:Ping").unwrap(), Message::Ping);
+    }
+
+    #[test]
+    fn deserializes_newtype_variant_from_single_entry_dict() {
+	assert_eq!(
+	    from_bytes::<Message>(b"d4:Text5:helloe").unwrap(),
+	    Message::Text("hello".to_string())
+	);
+    }
+
+    #[test]
+    fn deserializes_tuple_variant_from_dict_of_list() {
+	assert_eq!(
+	    from_bytes::<Message>(b"d5:Pointli1ei2eee").unwrap(),
+	    Message::Point(1, 2)
+	);
+    }
+
+    #[test]
+    fn deserializes_struct_variant_from_dict_of_dict() {
+	assert_eq!(
+	    from_bytes::<Message>(b"d5:Logind2:idi5e4:name1:aee").unwrap(),
+	    Message::Login{ name: "a".to_string(), id: 5 }
+	);
+    }
+}
@@ -0,0 +1,131 @@
+use std::io;
+use std::io::Write;
+
+use crate::{DictEntry, Element};
+
+fn write_bytestring<W: Write>(bytes: &[u8], writer: &mut W) -> io::Result<()> {
+    write!(writer, "{}:", bytes.len())?;
+    writer.write_all(bytes)?;
+
+    Ok(())
+}
+
+fn write_integer<W: Write>(value: i64, writer: &mut W) -> io::Result<()> {
+    write!(writer, "i{}e", value)
+}
+
+fn write_list<W: Write>(list: &[Element], writer: &mut W) -> io::Result<()> {
+    writer.write_all(b"l")?;
+
+    for element in list {
+	write_element(element, writer)?;
+    }
+
+    writer.write_all(b"e")
+}
+
+fn write_dict<W: Write>(dict: &[DictEntry], writer: &mut W) -> io::Result<()> {
+    // canonical bencode: dict keys are sorted as raw byte strings
+    let mut entries: Vec<&DictEntry> = dict.iter().collect();
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+
+    writer.write_all(b"d")?;
+
+    for entry in entries {
+	write_bytestring(&entry.key, writer)?;
+	write_element(&entry.value, writer)?;
+    }
+
+    writer.write_all(b"e")
+}
+
+/// Writes a single `Element` to `writer` in bencode form, sorting dict keys
+/// as raw byte strings so output is canonical.
+pub fn write_element<W: Write>(element: &Element, writer: &mut W) -> io::Result<()> {
+    match element {
+	Element::ByteString(s) => write_bytestring(s, writer),
+	Element::Integer(i) => write_integer(*i, writer),
+	Element::List(list) => write_list(list, writer),
+	Element::Dict(dict) => write_dict(dict, writer),
+    }
+}
+
+/// Writes a sequence of top-level `Element`s to `writer` in bencode form.
+pub fn write_elements<W: Write>(elements: &[Element], writer: &mut W) -> io::Result<()> {
+    for element in elements {
+	write_element(element, writer)?;
+    }
+
+    Ok(())
+}
+
+/// Encodes a single `Element` to a freshly allocated `Vec<u8>`.
+pub fn encode_element(element: &Element) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::new();
+    // writing to a Vec<u8> can't fail
+    write_element(element, &mut buf).unwrap();
+
+    buf
+}
+
+/// Encodes a sequence of top-level `Element`s to a freshly allocated `Vec<u8>`.
+pub fn encode(elements: &[Element]) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::new();
+    write_elements(elements, &mut buf).unwrap();
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_bytestring_happy_path() {
+	assert_eq!(encode_element(&Element::ByteString(b"announce".to_vec())), b"8:announce");
+	assert_eq!(encode_element(&Element::ByteString(vec![])), b"0:");
+    }
+
+    #[test]
+    fn encode_integer_happy_path() {
+	assert_eq!(encode_element(&Element::Integer(10)), b"i10e");
+	assert_eq!(encode_element(&Element::Integer(-10)), b"i-10e");
+	assert_eq!(encode_element(&Element::Integer(0)), b"i0e");
+    }
+
+    #[test]
+    fn encode_list_happy_path() {
+	let list = Element::List(vec![
+	    Element::Integer(10),
+	    Element::Integer(1),
+	    Element::List(vec![
+		Element::ByteString(b"b".to_vec()),
+	    ]),
+	]);
+
+	assert_eq!(encode_element(&list), b"li10ei1el1:bee");
+    }
+
+    #[test]
+    fn encode_dict_sorts_keys() {
+	let dict = Element::Dict(vec![
+	    DictEntry{ key: b"creation date".to_vec(), value: Element::Integer(1662813552) },
+	    DictEntry{ key: b"announce".to_vec(), value: Element::ByteString(b"http://bttracker.debian.org:6969/announce".to_vec()) },
+	]);
+
+	assert_eq!(
+	    encode_element(&dict),
+	    b"d8:announce41:http://bttracker.debian.org:6969/announce13:creation datei1662813552ee"
+	);
+    }
+
+    #[test]
+    fn encode_round_trips_with_decode() {
+	let input: &[u8] = b"d8:announce41:http://bttracker.debian.org:6969/announce13:creation datei1662813552ee";
+
+	match crate::decode(input) {
+	    crate::DecodedDocument::Ok(elements) => assert_eq!(encode(&elements), input),
+	    crate::DecodedDocument::Err(e) => panic!("failed to decode fixture: {}", e),
+	}
+    }
+}
@@ -1,4 +1,12 @@
 use base64;
+use memchr::memchr;
+use std::io::Read;
+
+mod encoder;
+pub use encoder::{encode, encode_element, write_element, write_elements};
+
+pub mod ser;
+pub mod de;
 
 #[derive(Debug)]
 #[derive(PartialEq)]
@@ -12,6 +20,11 @@ pub enum DecodeErrorType {
     MissingEndDelimiter, // e
     MissingStartDelimiter, // l,d,i
     NothingToDecode,
+    IoError,
+    NonCanonicalInteger,
+    UnsortedDictKeys,
+    DuplicateDictKey,
+    IntegerOverflow,
 }
 
 #[derive(Debug)]
@@ -43,6 +56,7 @@ pub enum Element {
 #[derive(PartialEq)]
 struct ElementDecoded {
     element: Element,
+    start_offset: usize,
     end_offset: usize,
 }
 
@@ -133,29 +147,205 @@ impl std::fmt::Display for DecodedDocument {
     }
 }
 
-const COLON: &u8 = &b':';
+impl Element {
+    /// Returns the dict entries if this element is a `Dict`.
+    pub fn as_dict(&self) -> Option<&[DictEntry]> {
+	match self {
+	    Element::Dict(entries) => Some(entries),
+	    _ => None,
+	}
+    }
+
+    /// Returns the raw bytes if this element is a `ByteString`.
+    pub fn as_bytestring(&self) -> Option<&[u8]> {
+	match self {
+	    Element::ByteString(bytes) => Some(bytes),
+	    _ => None,
+	}
+    }
+
+    /// Looks up `key` among this element's dict entries by raw key bytes.
+    pub fn dict_get(&self, key: &[u8]) -> Option<&Element> {
+	self.as_dict()?.iter().find(|entry| entry.key == key).map(|entry| &entry.value)
+    }
+}
+
 const MINUS: &u8 = &b'-';
-const D: &u8 = &b'd';
-const E: &u8 = &b'e';
-const I: &u8 = &b'i';
-const L: &u8 = &b'l';
+const COLON: u8 = b':';
+const D: u8 = b'd';
+const E: u8 = b'e';
+const I: u8 = b'i';
+const L: u8 = b'l';
+
+fn io_error(offset: usize) -> DecodeError {
+    DecodeError{
+	msg: "I/O error while reading input",
+	offset,
+	error_type: DecodeErrorType::IoError,
+    }
+}
 
-fn decode_ascii_integer(data: &[u8]) -> Result<i64, DecodeError> {
-    let mut negative = false;
-    let mut val: i64 = 0;
-    let mut iter = data.iter();
+/// Pulls bencode bytes from a `Read` on demand instead of requiring the
+/// whole document up front, buffering only as much as delimiters/lengths
+/// demand so an arbitrarily large torrent can be decoded without reading
+/// the whole file into memory before parsing even starts. This only
+/// bounds memory *across* elements, though: a single large byte string
+/// (e.g. a torrent's `pieces` field) is still materialized in full as one
+/// `Element::ByteString`, since that's what the decoded representation
+/// holds.
+pub struct Decoder<R: Read> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    base_offset: usize,
+    canonical: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(reader: R) -> Self {
+	Decoder{
+	    reader,
+	    buf: Vec::new(),
+	    pos: 0,
+	    base_offset: 0,
+	    canonical: false,
+	}
+    }
+
+    /// Like `new`, but also enforces canonical bencode: no leading-zero or
+    /// negative-zero integers or byte-string lengths, and dict keys must be
+    /// in strictly ascending raw-byte order with no duplicates.
+    pub fn new_strict(reader: R) -> Self {
+	Decoder{
+	    reader,
+	    buf: Vec::new(),
+	    pos: 0,
+	    base_offset: 0,
+	    canonical: true,
+	}
+    }
+
+    fn offset(&self) -> usize {
+	self.base_offset + self.pos
+    }
+
+    // drop already-consumed bytes so the buffer doesn't grow forever
+    fn compact(&mut self) {
+	if self.pos > 0 {
+	    self.buf.drain(0..self.pos);
+	    self.base_offset += self.pos;
+	    self.pos = 0;
+	}
+    }
+
+    // make sure at least `want` bytes are buffered beyond the read cursor; returns false on EOF
+    fn fill(&mut self, want: usize) -> std::io::Result<bool> {
+	let mut chunk = [0u8; 8192];
+	while self.buf.len() - self.pos < want {
+	    let n = self.reader.read(&mut chunk)?;
+	    if n == 0 {
+		return Ok(false);
+	    }
+	    self.buf.extend_from_slice(&chunk[..n]);
+	}
+
+	Ok(true)
+    }
+
+    fn peek(&mut self) -> std::io::Result<Option<u8>> {
+	if self.fill(1)? {
+	    Ok(Some(self.buf[self.pos]))
+	} else {
+	    Ok(None)
+	}
+    }
 
-    if let Some(n) = iter.next() {
-	if n == MINUS {
-	    // consume the byte
-	    negative = true;
+    fn consume(&mut self, n: usize) {
+	self.pos += n;
+	self.compact();
+    }
+
+    // owned copy so callers aren't stuck holding a borrow of `self`
+    fn take_owned(&mut self, n: usize) -> std::io::Result<Option<Vec<u8>>> {
+	if self.fill(n)? {
+	    Ok(Some(self.buf[self.pos..self.pos + n].to_vec()))
 	} else {
-	    // reset the iterator
-	    iter = data.iter();
+	    Ok(None)
+	}
+    }
+
+    // like `take_owned`, but also consumes the bytes it returns. Unlike
+    // `take_owned`, this never routes bytes beyond what's already buffered
+    // through `buf` first -- it reads the remainder straight off `reader`
+    // into the result -- so copying out a single large byte string (e.g. a
+    // torrent's `pieces` field) doesn't also briefly hold a second full
+    // copy of it sitting in `buf`
+    fn take_owned_and_consume(&mut self, n: usize) -> std::io::Result<Option<Vec<u8>>> {
+	let buffered = self.buf.len() - self.pos;
+
+	if buffered >= n {
+	    let result = self.buf[self.pos..self.pos + n].to_vec();
+	    self.consume(n);
+	    return Ok(Some(result));
+	}
+
+	let mut result = self.buf[self.pos..].to_vec();
+	self.consume(buffered);
+	result.reserve(n - result.len());
+
+	let mut chunk = [0u8; 8192];
+	while result.len() < n {
+	    let want = std::cmp::min(chunk.len(), n - result.len());
+	    match self.reader.read(&mut chunk[..want])? {
+		0 => return Ok(None),
+		read => {
+		    result.extend_from_slice(&chunk[..read]);
+		    self.base_offset += read;
+		},
+	    }
+	}
+
+	Ok(Some(result))
+    }
+
+    // offset of the first `target` at or after the read cursor, relative to
+    // it; SIMD-accelerated via memchr, since a linear byte-by-byte scan is
+    // the hot path when decoding a multi-megabyte byte string (e.g. a
+    // torrent's `pieces` field)
+    fn find(&mut self, target: u8) -> std::io::Result<Option<usize>> {
+	let mut scanned = 0;
+
+	loop {
+	    if let Some(idx) = memchr(target, &self.buf[self.pos + scanned..]) {
+		return Ok(Some(scanned + idx));
+	    }
+	    scanned = self.buf.len() - self.pos;
+
+	    if !self.fill(scanned + 1)? {
+		return Ok(None);
+	    }
+	}
+    }
+
+    /// Decodes and returns the next top-level `Element`, or `None` once the
+    /// reader is exhausted.
+    pub fn next_element(&mut self) -> Option<Result<Element, DecodeError>> {
+	match self.peek() {
+	    Ok(Some(_)) => match dispatch(self) {
+		DecodeResult::Ok(result) => Some(Ok(result.element)),
+		DecodeResult::Err(e) => Some(Err(e)),
+	    },
+	    Ok(None) => None,
+	    Err(_) => Some(Err(io_error(self.offset()))),
 	}
     }
+}
 
-    // there is a failure case when there is nothing to decode, but this doesn't account for only having a `-`...
+// parses left-to-right with checked arithmetic: bencode integers are
+// arbitrary-precision per spec, and torrent length fields can approach
+// i64::MAX, so the naive power-of-ten sum this used to do would overflow
+// (and panic in debug builds) instead of reporting a clean error
+fn decode_ascii_integer(data: &[u8]) -> Result<i64, DecodeError> {
     if data.len() == 0 {
 	return Err(DecodeError{
 	    msg: "Nothing to decode",
@@ -164,233 +354,431 @@ fn decode_ascii_integer(data: &[u8]) -> Result<i64, DecodeError> {
 	});
     }
 
-    for (index, n) in iter.rev().enumerate() {
-	// guard for index greater than u32?
-	// more general guard against integer overflow?
-	val += ((n - 0x30) as i64) * 10i64.pow(index.try_into().unwrap());
+    let (negative, digits) = match data.split_first() {
+	Some((n, rest)) if n == MINUS => (true, rest),
+	_ => (false, data),
+    };
+
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+	return Err(DecodeError{
+	    msg: "Can't decode integer: expected an optional leading '-' followed by ASCII digits",
+	    offset: 0,
+	    error_type: DecodeErrorType::InvalidIntegerValue,
+	});
+    }
+
+    // accumulate as a negative value regardless of sign, since i64's negative
+    // range holds one more value than its positive range (i64::MIN has no
+    // positive counterpart); negate only once at the end for positive inputs
+    let mut val: i64 = 0;
+    for n in digits {
+	let digit = (n - 0x30) as i64;
+	val = val.checked_mul(10)
+	    .and_then(|v| v.checked_sub(digit))
+	    .ok_or(DecodeError{
+		msg: "Can't decode integer: value overflows i64",
+		offset: 0,
+		error_type: DecodeErrorType::IntegerOverflow,
+	    })?;
     }
 
-    if negative {
-	val = -val;
+    if !negative {
+	val = val.checked_neg().ok_or(DecodeError{
+	    msg: "Can't decode integer: value overflows i64",
+	    offset: 0,
+	    error_type: DecodeErrorType::IntegerOverflow,
+	})?;
     }
-    
+
     return Ok(val);
 }
 
-fn decode_bytestring(data: &[u8]) -> DecodeResult {
-    // wtf is this position syntax?
-    match data.iter().position(|d_var| d_var == COLON) {
-	Some(size_end) => {
-	    match decode_ascii_integer(&data[0..size_end]) {
+// canonical bencode forbids leading zeros (`03`) and negative zero/leading
+// zeros (`-0`, `-03`); `0` on its own is the only valid zero
+fn is_canonical_integer(digits: &[u8]) -> bool {
+    match digits.split_first() {
+	Some((b'-', rest)) => !rest.is_empty() && rest[0] != b'0',
+	Some((b'0', rest)) => rest.is_empty(),
+	Some(_) => true,
+	None => false,
+    }
+}
+
+fn decode_bytestring<R: Read>(decoder: &mut Decoder<R>) -> DecodeResult {
+    let start = decoder.offset();
+
+    match decoder.find(COLON) {
+	Ok(Some(size_end)) => {
+	    let digits = match decoder.take_owned(size_end) {
+		Ok(Some(digits)) => digits,
+		Ok(None) => return DecodeResult::Err(io_error(start)), // find() already proved these bytes are buffered
+		Err(_) => return DecodeResult::Err(io_error(start)),
+	    };
+
+	    if decoder.canonical && !is_canonical_integer(&digits) {
+		return DecodeResult::Err(DecodeError{
+		    msg: "Can't decode size integer for string: not canonically encoded",
+		    offset: start,
+		    error_type: DecodeErrorType::NonCanonicalInteger,
+		});
+	    }
+
+	    match decode_ascii_integer(&digits) {
 		Ok(length) => {
-		    let decode_start: usize = size_end + 1;
-		    let decode_end: usize = decode_start + (length as usize);
-		    let mut element: Vec<u8> = Vec::new();
-		    element.extend_from_slice(&data[decode_start..decode_end]);
+		    // a bencode byte-string length can never legitimately be negative;
+		    // letting one through would wrap `as usize` into a huge value that
+		    // `take_owned_and_consume` would then try to reserve and read
+		    if length < 0 {
+			return DecodeResult::Err(DecodeError{
+			    msg: "Can't decode bytestring: length is negative",
+			    offset: start,
+			    error_type: DecodeErrorType::InvalidByteStringSize,
+			});
+		    }
 
-		    DecodeResult::Ok(ElementDecoded{
-			element: Element::ByteString(element),
-			end_offset: decode_end,
-		    })
+		    decoder.consume(size_end + 1); // trim digits + ':'
+
+		    match decoder.take_owned_and_consume(length as usize) {
+			Ok(Some(element)) => {
+			    DecodeResult::Ok(ElementDecoded{
+				element: Element::ByteString(element),
+				start_offset: start,
+				end_offset: decoder.offset(),
+			    })
+			},
+
+			Ok(None) => DecodeResult::Err(DecodeError{
+			    msg: "Can't decode bytestring: input ended before declared length",
+			    offset: start,
+			    error_type: DecodeErrorType::InvalidByteStringData,
+			}),
+
+			Err(_) => DecodeResult::Err(io_error(decoder.offset())),
+		    }
 		},
 
 		Err(e) => DecodeResult::Err(DecodeError{
 		    msg: "Can't decode size integer for string",
-		    offset: e.offset,
+		    offset: start + e.offset,
 		    error_type: DecodeErrorType::InvalidByteStringSize,
 		}),
 	    }
 	},
-	None => DecodeResult::Err(DecodeError{
+
+	Ok(None) => DecodeResult::Err(DecodeError{
 	    msg: "Can't decode bytestring from data: missing ':'",
-	    offset: 0,
+	    offset: start,
 	    error_type: DecodeErrorType::MissingDelimiter,
 	}),
+
+	Err(_) => DecodeResult::Err(io_error(start)),
     }
 }
 
-fn decode_integer(data: &[u8]) -> DecodeResult {
-    if &data[0] != I {
-	return DecodeResult::Err(DecodeError{
+fn decode_integer<R: Read>(decoder: &mut Decoder<R>) -> DecodeResult {
+    let start = decoder.offset();
+
+    match decoder.peek() {
+	Ok(Some(b)) if b == I => {},
+	Ok(_) => return DecodeResult::Err(DecodeError{
 	    msg: "Can't decode integer: missing leading 'i'",
-	    offset: 0,
+	    offset: start,
 	    error_type: DecodeErrorType::MissingStartDelimiter,
-	});
+	}),
+	Err(_) => return DecodeResult::Err(io_error(start)),
     }
 
-    match data.iter().position(|d_var| d_var == E) {
-	Some(integer_end) => {
-	    match decode_ascii_integer(&data[1..integer_end]) {
+    match decoder.find(E) {
+	Ok(Some(integer_end)) => {
+	    let digits = match decoder.take_owned(integer_end) {
+		Ok(Some(digits)) => digits[1..].to_vec(), // drop the leading 'i'
+		Ok(None) => return DecodeResult::Err(io_error(start)),
+		Err(_) => return DecodeResult::Err(io_error(start)),
+	    };
+
+	    if decoder.canonical && !is_canonical_integer(&digits) {
+		return DecodeResult::Err(DecodeError{
+		    msg: "Can't decode integer: not canonically encoded",
+		    offset: start,
+		    error_type: DecodeErrorType::NonCanonicalInteger,
+		});
+	    }
+
+	    match decode_ascii_integer(&digits) {
 		Ok(element) => {
+		    decoder.consume(integer_end + 1); // trim digits + 'e'
+
 		    DecodeResult::Ok(ElementDecoded{
 			element: Element::Integer(element),
-			end_offset: integer_end + 1,
+			start_offset: start,
+			end_offset: decoder.offset(),
 		    })
 		},
 
-		Err(_) => DecodeResult::Err(DecodeError{
-		    msg: "Can't decode integer",
-		    offset: 0,
-		    error_type: DecodeErrorType::InvalidIntegerValue,
+		Err(e) => DecodeResult::Err(DecodeError{
+		    offset: start + e.offset,
+		    ..e
 		})
 	    }
 	},
-	    
-	None => DecodeResult::Err(DecodeError{
+
+	Ok(None) => DecodeResult::Err(DecodeError{
 	    msg: "Can't decode integer: missing end 'e'",
-	    offset: 0,
+	    offset: start,
 	    error_type: DecodeErrorType::MissingEndDelimiter,
 	}),
+
+	Err(_) => DecodeResult::Err(io_error(start)),
     }
 }
 
-fn decode_list(data: &[u8]) -> DecodeResult {
-    let mut offset = 0;
-    let mut ret: Vec<Element> = Vec::new();
+fn decode_list<R: Read>(decoder: &mut Decoder<R>) -> DecodeResult {
+    let start = decoder.offset();
 
-    if &data[0] != L {
-	return DecodeResult::Err(DecodeError{
+    match decoder.peek() {
+	Ok(Some(b)) if b == L => {},
+	Ok(_) => return DecodeResult::Err(DecodeError{
 	    msg: "Can't decode list: missing leading 'l'",
-	    offset: offset,
+	    offset: start,
 	    error_type: DecodeErrorType::MissingStartDelimiter,
-	});
+	}),
+	Err(_) => return DecodeResult::Err(io_error(start)),
     }
-    offset += 1; // trim leading l
-
-    while offset < data.len() {
-	match &data[offset] {
-	    E => return DecodeResult::Ok(ElementDecoded{ 
-		element: Element::List(ret),
-		end_offset: offset + 1, // trim trailling 'e'
-	    }), 
-	    _ => {
-		match dispatch(&data[offset..]) {
-		    DecodeResult::Ok(result) => {
-			ret.push(result.element);
-			offset += result.end_offset;
-		    },
+    decoder.consume(1); // trim leading l
+
+    let mut ret: Vec<Element> = Vec::new();
+
+    loop {
+	match decoder.peek() {
+	    Ok(Some(b)) if b == E => {
+		decoder.consume(1); // trim trailing 'e'
+		return DecodeResult::Ok(ElementDecoded{
+		    element: Element::List(ret),
+		    start_offset: start,
+		    end_offset: decoder.offset(),
+		});
+	    },
 
-		    DecodeResult::Err(e) => return DecodeResult::Err(DecodeError{
-			msg: e.msg,
-			offset: e.offset + offset,
-			error_type: e.error_type,
-		    }),
+	    Ok(Some(_)) => {
+		match dispatch(decoder) {
+		    DecodeResult::Ok(result) => ret.push(result.element),
+		    DecodeResult::Err(e) => return DecodeResult::Err(e),
 		}
 	    },
+
+	    Ok(None) => return DecodeResult::Err(DecodeError{
+		msg: "Can't decode list: ran out of chars before trailing 'e'",
+		offset: decoder.offset(),
+		error_type: DecodeErrorType::MissingEndDelimiter,
+	    }),
+
+	    Err(_) => return DecodeResult::Err(io_error(decoder.offset())),
 	}
     }
-
-    return DecodeResult::Err(DecodeError{
-	msg: "Can't decode list: ran out of chars before trailing 'e'",
-	offset: offset,
-	error_type: DecodeErrorType::MissingEndDelimiter,
-    });
 }
 
-fn decode_dict(data: &[u8]) -> DecodeResult {
-    let mut offset = 0;
-    let mut ret: Vec<DictEntry> = Vec::new();
+fn decode_dict<R: Read>(decoder: &mut Decoder<R>) -> DecodeResult {
+    let start = decoder.offset();
 
-    if &data[0] != D {
-	return DecodeResult::Err(DecodeError{
+    match decoder.peek() {
+	Ok(Some(b)) if b == D => {},
+	Ok(_) => return DecodeResult::Err(DecodeError{
 	    msg: "Can't decode list: missing leading 'd'",
-	    offset: offset,
+	    offset: start,
 	    error_type: DecodeErrorType::MissingStartDelimiter,
-	});
+	}),
+	Err(_) => return DecodeResult::Err(io_error(start)),
     }
-    offset += 1;
+    decoder.consume(1);
 
-    while offset < data.len() {
-	match &data[offset] {
-	    E => return DecodeResult::Ok(ElementDecoded{
-		element: Element::Dict(ret),
-		end_offset: offset + 1, // trim trailing 'e'
-	    }),
-	    _ => {
-		match decode_bytestring(&data[offset..]) {
-		    DecodeResult::Ok(decode_key) => {
-			match decode_key.element {
+    let mut ret: Vec<DictEntry> = Vec::new();
+    let mut last_key: Option<Vec<u8>> = None;
+
+    loop {
+	match decoder.peek() {
+	    Ok(Some(b)) if b == E => {
+		decoder.consume(1); // trim trailing 'e'
+		return DecodeResult::Ok(ElementDecoded{
+		    element: Element::Dict(ret),
+		    start_offset: start,
+		    end_offset: decoder.offset(),
+		});
+	    },
+
+	    Ok(Some(_)) => {
+		let key_start = decoder.offset();
+
+		match decode_bytestring(decoder) {
+		    DecodeResult::Ok(decoded_key) => {
+			match decoded_key.element {
 			    Element::ByteString(key) => {
-				match dispatch(&data[offset+decode_key.end_offset..]) {
-				    DecodeResult::Ok(result) => {
-					ret.push(DictEntry{ key: key, value: result.element });
-					offset += result.end_offset + decode_key.end_offset
-				    },
+				if decoder.canonical {
+				    match &last_key {
+					Some(previous) if *previous == key => return DecodeResult::Err(DecodeError{
+					    msg: "Can't decode dict: duplicate key",
+					    offset: key_start,
+					    error_type: DecodeErrorType::DuplicateDictKey,
+					}),
+					Some(previous) if *previous > key => return DecodeResult::Err(DecodeError{
+					    msg: "Can't decode dict: keys are not in ascending order",
+					    offset: key_start,
+					    error_type: DecodeErrorType::UnsortedDictKeys,
+					}),
+					_ => {},
+				    }
+				    last_key = Some(key.clone());
+				}
 
-				    DecodeResult::Err(e) => return DecodeResult::Err(DecodeError{
-					msg: e.msg,
-					offset: e.offset + offset,
-					error_type: e.error_type,
-				    }),
+				match dispatch(decoder) {
+				    DecodeResult::Ok(result) => ret.push(DictEntry{ key, value: result.element }),
+				    DecodeResult::Err(e) => return DecodeResult::Err(e),
 				}
 			    },
 
 			    _ => return DecodeResult::Err(DecodeError{
 				msg: "Can't decode dict key: got non bytestring element",
-				offset: offset,
+				offset: start,
 				error_type: DecodeErrorType::InvalidDictKey,
 			    })
 			}
 		    },
 
-		    DecodeResult::Err(e) => {
-			return DecodeResult::Err(DecodeError{
-			    msg: e.msg,
-			    offset: e.offset + offset,
-			    error_type: e.error_type,
-			})
-		    },
+		    DecodeResult::Err(e) => return DecodeResult::Err(e),
 		}
 	    },
+
+	    Ok(None) => return DecodeResult::Err(DecodeError{
+		msg: "Can't decode dict: ran out of chars",
+		offset: decoder.offset(),
+		error_type: DecodeErrorType::MissingEndDelimiter,
+	    }),
+
+	    Err(_) => return DecodeResult::Err(io_error(decoder.offset())),
 	}
     }
-
-    return DecodeResult::Err(DecodeError{
-	msg: "Can't decode dict: ran out of chars",
-	offset: offset,
-	error_type: DecodeErrorType::MissingEndDelimiter,
-    });
 }
 
-fn dispatch(data: &[u8]) -> DecodeResult {
-    match &data[0] {
-	0x30 ..= 0x39 => decode_bytestring(data), // 0 - 9 in ascii
-	I => decode_integer(data),
-	L => decode_list(data),
-	D => decode_dict(data),
-	_ => DecodeResult::Err(DecodeError{
-	    msg: "Unable to continue parsing: can't determine where to dispatch",
-	    offset: 0,
-	    error_type: DecodeErrorType::DispatchFailed,
+fn dispatch<R: Read>(decoder: &mut Decoder<R>) -> DecodeResult {
+    match decoder.peek() {
+	Ok(Some(b)) => match b {
+	    0x30 ..= 0x39 => decode_bytestring(decoder), // 0 - 9 in ascii
+	    I => decode_integer(decoder),
+	    L => decode_list(decoder),
+	    D => decode_dict(decoder),
+	    _ => DecodeResult::Err(DecodeError{
+		msg: "Unable to continue parsing: can't determine where to dispatch",
+		offset: decoder.offset(),
+		error_type: DecodeErrorType::DispatchFailed,
+	    }),
+	},
+
+	Ok(None) => DecodeResult::Err(DecodeError{
+	    msg: "Nothing to decode",
+	    offset: decoder.offset(),
+	    error_type: DecodeErrorType::NothingToDecode,
 	}),
+
+	Err(_) => DecodeResult::Err(io_error(decoder.offset())),
     }
 }
 
+/// Decodes a complete in-memory bencode document. A thin wrapper around
+/// `Decoder` for callers that already have the whole input as a slice.
 pub fn decode(data: &[u8]) -> DecodedDocument {
-    let mut offset = 0;
+    let mut decoder = Decoder::new(std::io::Cursor::new(data));
+    let mut ret: Vec<Element> = Vec::new();
+
+    loop {
+	match decoder.next_element() {
+	    Some(Ok(element)) => ret.push(element),
+	    Some(Err(e)) => return DecodedDocument::Err(e),
+	    None => return DecodedDocument::Ok(ret),
+	}
+    }
+}
+
+/// Like `decode`, but rejects non-canonical bencode: leading-zero or
+/// negative-zero integers and byte-string lengths, and dict keys that
+/// aren't in strictly ascending raw-byte order or contain duplicates.
+pub fn decode_strict(data: &[u8]) -> DecodedDocument {
+    let mut decoder = Decoder::new_strict(std::io::Cursor::new(data));
     let mut ret: Vec<Element> = Vec::new();
 
-    while offset < data.len() {
-	match dispatch(&data[offset..]) {
-	    DecodeResult::Ok(result) => {
-		ret.push(result.element);
-		offset += result.end_offset;
+    loop {
+	match decoder.next_element() {
+	    Some(Ok(element)) => ret.push(element),
+	    Some(Err(e)) => return DecodedDocument::Err(e),
+	    None => return DecodedDocument::Ok(ret),
+	}
+    }
+}
+
+/// Finds `key` in the top-level dict decoded from `data` and returns the
+/// byte span `[start, end)` its value occupies in `data`, exactly as it
+/// appeared in the source. `Ok(None)` means `data` isn't a dict, or the dict
+/// has no such key. Useful for hashing a sub-element's raw bytes (e.g. a
+/// BitTorrent info_hash), where re-encoding the decoded value could change
+/// the hash.
+pub fn find_dict_value_span(data: &[u8], key: &[u8]) -> Result<Option<(usize, usize)>, DecodeError> {
+    let mut decoder = Decoder::new(std::io::Cursor::new(data));
+
+    match decoder.peek() {
+	Ok(Some(b)) if b == D => {},
+	Ok(_) => return Ok(None),
+	Err(_) => return Err(io_error(decoder.offset())),
+    }
+    decoder.consume(1);
+
+    loop {
+	match decoder.peek() {
+	    Ok(Some(b)) if b == E => return Ok(None),
+
+	    Ok(Some(_)) => {
+		match decode_bytestring(&mut decoder) {
+		    DecodeResult::Ok(decoded_key) => {
+			match decoded_key.element {
+			    Element::ByteString(entry_key) => {
+				match dispatch(&mut decoder) {
+				    DecodeResult::Ok(result) => {
+					if entry_key == key {
+					    return Ok(Some((result.start_offset, result.end_offset)));
+					}
+				    },
+				    DecodeResult::Err(e) => return Err(e),
+				}
+			    },
+
+			    _ => return Err(DecodeError{
+				msg: "Can't decode dict key: got non bytestring element",
+				offset: decoder.offset(),
+				error_type: DecodeErrorType::InvalidDictKey,
+			    }),
+			}
+		    },
+
+		    DecodeResult::Err(e) => return Err(e),
+		}
 	    },
 
-	    DecodeResult::Err(e) => return DecodedDocument::Err(DecodeError{
-		msg: e.msg,
-		offset: e.offset + offset,
-		error_type: e.error_type,
+	    Ok(None) => return Err(DecodeError{
+		msg: "Can't decode dict: ran out of chars",
+		offset: decoder.offset(),
+		error_type: DecodeErrorType::MissingEndDelimiter,
 	    }),
+
+	    Err(_) => return Err(io_error(decoder.offset())),
 	}
     }
-
-    return DecodedDocument::Ok(ret);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Cursor;
+
+    fn decoder_for(data: &[u8]) -> Decoder<Cursor<&[u8]>> {
+	Decoder::new(Cursor::new(data))
+    }
 
     #[test]
     fn decode_ascii_integer_happy_path() {
@@ -399,70 +787,124 @@ mod tests {
 	assert_eq!(decode_ascii_integer(b"0").unwrap(), 0);
 	assert_eq!(decode_ascii_integer(b"12345678").unwrap(), 12345678);
 	assert_eq!(decode_ascii_integer(b"-12345678").unwrap(), -12345678);
+	assert_eq!(decode_ascii_integer(format!("{}", i64::MAX).as_bytes()).unwrap(), i64::MAX);
+	assert_eq!(decode_ascii_integer(format!("{}", i64::MIN).as_bytes()).unwrap(), i64::MIN);
+    }
+
+    #[test]
+    fn decode_ascii_integer_rejects_overflow() {
+	// i64::MAX + 1
+	match decode_ascii_integer(b"9223372036854775808") {
+	    Err(e) => assert_eq!(e.error_type, DecodeErrorType::IntegerOverflow),
+	    other => panic!("expected an IntegerOverflow error, got {:?}", other),
+	}
     }
-    
+
+    #[test]
+    fn decode_ascii_integer_rejects_non_digit_bytes() {
+	match decode_ascii_integer(b"12a4") {
+	    Err(e) => assert_eq!(e.error_type, DecodeErrorType::InvalidIntegerValue),
+	    other => panic!("expected an InvalidIntegerValue error, got {:?}", other),
+	}
+
+	match decode_ascii_integer(b"-") {
+	    Err(e) => assert_eq!(e.error_type, DecodeErrorType::InvalidIntegerValue),
+	    other => panic!("expected an InvalidIntegerValue error, got {:?}", other),
+	}
+    }
+
     #[test]
     fn decode_string_happy_path() {
 	let input = b"0:";
-	let result = decode_bytestring(input);
+	let result = decode_bytestring(&mut decoder_for(input));
 	assert_eq!(result, DecodeResult::Ok(ElementDecoded{
 	    element: Element::ByteString(vec![]),
+	    start_offset: 0,
 	    end_offset: input.len(),
 	}));
 
 	let input = b"8:announce";
-	let result = decode_bytestring(input);
+	let result = decode_bytestring(&mut decoder_for(input));
 	assert_eq!(result, DecodeResult::Ok(ElementDecoded{
 	    element: Element::ByteString("announce".as_bytes().to_vec()),
+	    start_offset: 0,
 	    end_offset: input.len(),
 	}));
 
 	let input = b"41:http://bttracker.debian.org:6969/announce";
-	let result = decode_bytestring(input);
+	let result = decode_bytestring(&mut decoder_for(input));
 	assert_eq!(result, DecodeResult::Ok(ElementDecoded{
 	    element: Element::ByteString("http://bttracker.debian.org:6969/announce".as_bytes().to_vec()),
+	    start_offset: 0,
 	    end_offset: input.len(),
 	}));
 
 	let input = b"8:announce41:http://bttracker.debian.org:6969/announce7:comment";
-	let result = decode_bytestring(input);
+	let result = decode_bytestring(&mut decoder_for(input));
 	assert_eq!(result, DecodeResult::Ok(ElementDecoded{
 	    element: Element::ByteString("announce".as_bytes().to_vec()),
+	    start_offset: 0,
 	    end_offset: input.len() - "41:http://bttracker.debian.org:6969/announce7:comment".len(),
 	}));
     }
 
+    #[test]
+    fn decode_string_rejects_negative_length() {
+	// a negative length must never reach the `as usize` cast that feeds
+	// `take_owned_and_consume`, or it wraps into a huge value to reserve and read
+	let input = b"-3:abc";
+	match decode_bytestring(&mut decoder_for(input)) {
+	    DecodeResult::Err(e) => assert_eq!(e.error_type, DecodeErrorType::InvalidByteStringSize),
+	    other => panic!("expected an InvalidByteStringSize error, got {:?}", other),
+	}
+    }
+
     #[test]
     fn decode_integer_happy_path() {
 	let input = b"i10e";
-	let result = decode_integer(input);
+	let result = decode_integer(&mut decoder_for(input));
 	assert_eq!(result, DecodeResult::Ok(ElementDecoded{
 	    element: Element::Integer(10),
+	    start_offset: 0,
 	    end_offset: input.len(),
 	}));
 
 	let input = b"i-10e";
-	let result = decode_integer(input);
+	let result = decode_integer(&mut decoder_for(input));
 	assert_eq!(result, DecodeResult::Ok(ElementDecoded{
 	    element: Element::Integer(-10),
+	    start_offset: 0,
 	    end_offset: input.len(),
 	}));
     }
 
+    #[test]
+    fn decode_integer_reports_overflow_at_the_right_offset() {
+	let input = b"i9223372036854775808e";
+	match decode_integer(&mut decoder_for(input)) {
+	    DecodeResult::Err(e) => {
+		assert_eq!(e.error_type, DecodeErrorType::IntegerOverflow);
+		assert_eq!(e.offset, 0);
+	    },
+	    other => panic!("expected an IntegerOverflow error, got {:?}", other),
+	}
+    }
+
     #[test]
     fn decode_list_happy_path() {
 	let input = b"le";
-	let result = decode_list(input);
+	let result = decode_list(&mut decoder_for(input));
 	assert_eq!(
 	    result,
 	    DecodeResult::Ok(ElementDecoded{
 		element: Element::List(vec![]),
+		start_offset: 0,
 		end_offset: input.len(),
 	    })
 	);
 
 	let input = b"li10ei1ee";
-	let result = decode_list(input);
+	let result = decode_list(&mut decoder_for(input));
 	assert_eq!(
 	    result,
 	    DecodeResult::Ok(ElementDecoded{
@@ -470,12 +912,13 @@ mod tests {
 		    Element::Integer(10),
 		    Element::Integer(1)
 		]),
+		start_offset: 0,
 		end_offset: input.len(),
 	    })
 	);
 
 	let input = b"li10ei1ee1:a";
-	let result = decode_list(input);
+	let result = decode_list(&mut decoder_for(input));
 	assert_eq!(
 	    result,
 	    DecodeResult::Ok(ElementDecoded{
@@ -483,12 +926,13 @@ mod tests {
 		    Element::Integer(10),
 		    Element::Integer(1)
 		]),
+		start_offset: 0,
 		end_offset: input.len() - "1:a".len()
 	    })
 	);
 
 	let input = b"li10ei1el1:bee1:a";
-	let result = decode_list(input);
+	let result = decode_list(&mut decoder_for(input));
 	assert_eq!(
 	    result,
 	    DecodeResult::Ok(ElementDecoded{
@@ -499,6 +943,7 @@ mod tests {
 			Element::ByteString(b"b".to_vec()),
 		    ]),
 		]),
+		start_offset: 0,
 		end_offset: input.len() - "1:a".len(),
 	    })
 	);
@@ -507,29 +952,31 @@ mod tests {
     #[test]
     fn decode_dict_happy_path() {
 	let input = b"de";
-	let result = decode_dict(input);
+	let result = decode_dict(&mut decoder_for(input));
 	assert_eq!(
 	    result,
 	    DecodeResult::Ok(ElementDecoded{
 		element: Element::Dict(vec![]),
+		start_offset: 0,
 		end_offset: input.len(),
 	    })
 	);
 
 	let input = b"d1:ai10ee";
-	let result = decode_dict(input);
+	let result = decode_dict(&mut decoder_for(input));
 	assert_eq!(
 	    result,
 	    DecodeResult::Ok(ElementDecoded{
 		element: Element::Dict(vec![
 		    DictEntry{ key: b"a".to_vec(), value: Element::Integer(10) }
 		]),
+		start_offset: 0,
 		end_offset: input.len(),
 	    })
 	);
 
 	let input = b"d4:listli10ei1el1:beee1:a";
-	let result = decode_dict(input);
+	let result = decode_dict(&mut decoder_for(input));
 	assert_eq!(
 	    result,
 	    DecodeResult::Ok(ElementDecoded{
@@ -545,28 +992,41 @@ mod tests {
 			])
 		    }
 		]),
+		start_offset: 0,
 		end_offset: input.len() - "1:a".len(),
 	    })
 	);
     }
-    
+
     #[test]
     fn dispatch_happy_path() {
 	let input = b"8:announce";
-	let result: DecodeResult = dispatch(input);
+	let result: DecodeResult = dispatch(&mut decoder_for(input));
 	assert_eq!(result, DecodeResult::Ok(ElementDecoded{
 	    element: Element::ByteString(b"announce".to_vec()),
+	    start_offset: 0,
 	    end_offset: input.len(),
 	}));
 
 	let input = b"i-18e";
-	let result: DecodeResult = dispatch(input);
+	let result: DecodeResult = dispatch(&mut decoder_for(input));
 	assert_eq!(result, DecodeResult::Ok(ElementDecoded{
 	    element: Element::Integer(-18),
+	    start_offset: 0,
 	    end_offset: input.len(),
 	}));
     }
 
+    #[test]
+    fn next_element_streams_over_a_reader() {
+	let input = b"8:announcei10e";
+	let mut decoder = decoder_for(input);
+
+	assert_eq!(decoder.next_element().unwrap().unwrap(), Element::ByteString(b"announce".to_vec()));
+	assert_eq!(decoder.next_element().unwrap().unwrap(), Element::Integer(10));
+	assert!(decoder.next_element().is_none());
+    }
+
     #[test]
     fn decode_happy_path() {
 	assert_eq!(
@@ -632,4 +1092,75 @@ mod tests {
 	    ])
 	);
     }
+
+    #[test]
+    fn decode_strict_accepts_canonical_encodings() {
+	assert_eq!(
+	    decode_strict(b"d8:announce7:tracker4:infoi0ee"),
+	    DecodedDocument::Ok(vec![
+		Element::Dict(vec![
+		    DictEntry{ key: b"announce".to_vec(), value: Element::ByteString(b"tracker".to_vec()) },
+		    DictEntry{ key: b"info".to_vec(), value: Element::Integer(0) },
+		]),
+	    ])
+	);
+    }
+
+    #[test]
+    fn decode_strict_rejects_leading_zero_integer() {
+	match decode_strict(b"i03e") {
+	    DecodedDocument::Err(e) => assert_eq!(e.error_type, DecodeErrorType::NonCanonicalInteger),
+	    other => panic!("expected a NonCanonicalInteger error, got {:?}", other),
+	}
+    }
+
+    #[test]
+    fn decode_strict_rejects_negative_zero() {
+	match decode_strict(b"i-0e") {
+	    DecodedDocument::Err(e) => assert_eq!(e.error_type, DecodeErrorType::NonCanonicalInteger),
+	    other => panic!("expected a NonCanonicalInteger error, got {:?}", other),
+	}
+    }
+
+    #[test]
+    fn decode_strict_rejects_leading_zero_string_length() {
+	match decode_strict(b"01:a") {
+	    DecodedDocument::Err(e) => assert_eq!(e.error_type, DecodeErrorType::NonCanonicalInteger),
+	    other => panic!("expected a NonCanonicalInteger error, got {:?}", other),
+	}
+    }
+
+    #[test]
+    fn decode_strict_rejects_unsorted_dict_keys() {
+	match decode_strict(b"d1:bi1e1:ai2ee") {
+	    DecodedDocument::Err(e) => assert_eq!(e.error_type, DecodeErrorType::UnsortedDictKeys),
+	    other => panic!("expected an UnsortedDictKeys error, got {:?}", other),
+	}
+    }
+
+    #[test]
+    fn decode_strict_rejects_duplicate_dict_keys() {
+	match decode_strict(b"d1:ai1e1:ai2ee") {
+	    DecodedDocument::Err(e) => assert_eq!(e.error_type, DecodeErrorType::DuplicateDictKey),
+	    other => panic!("expected a DuplicateDictKey error, got {:?}", other),
+	}
+    }
+
+    #[test]
+    fn decode_permits_non_canonical_encodings() {
+	assert_eq!(decode(b"i03e"), DecodedDocument::Ok(vec![Element::Integer(3)]));
+    }
+
+    #[test]
+    fn find_dict_value_span_pins_down_the_value_bytes() {
+	let input = b"d8:announce7:tracker4:infod6:lengthi10e4:name1:aee";
+	assert_eq!(find_dict_value_span(input, b"info"), Ok(Some((26, 49))));
+	assert_eq!(&input[26..49], b"d6:lengthi10e4:name1:ae".as_slice());
+    }
+
+    #[test]
+    fn find_dict_value_span_returns_none_for_missing_key() {
+	let input = b"d8:announce7:trackere";
+	assert_eq!(find_dict_value_span(input, b"info"), Ok(None));
+    }
 }
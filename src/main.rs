@@ -1,7 +1,8 @@
-use clap::{Arg, ArgMatches, Command, arg};
-use downpour::decode;
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command, arg};
+use downpour::{decode, encode, DecodedDocument, Decoder, Element};
+use sha1::{Digest, Sha1};
 use std::fs;
-use std::io::{Read, Stdin};
+use std::io::Write;
 
 fn cmd() -> Command {
     Command::new("downpour")
@@ -21,30 +22,110 @@ fn cmd() -> Command {
 		.about("Work with bencoded data from stdin")
 		.args([
 		    Arg::new("decode")
+			.long("decode")
+			.action(ArgAction::SetTrue)
 			.help("Decode bencoded data to json"),
 		    Arg::new("encode")
-			.help("TODO! Encode json to bencoding"),
-		    ]),
+			.long("encode")
+			.action(ArgAction::SetTrue)
+			.help("Re-encode bencoded data, canonicalizing dict key order"),
+		    ])
+		.group(
+		    ArgGroup::new("bencoding_mode")
+			.args(["decode", "encode"])
+			.required(true),
+		),
 	)
 }
 
+// info_hash needs the info dict's exact source bytes, so the whole file is
+// read up front rather than streamed through a `Decoder`; torrent metadata
+// is small compared to the piece data it describes. The element tree is
+// only built transiently to print it: name/announce are pulled back out of
+// `data` via `find_dict_value_span`, same as info_hash, rather than keeping
+// the fully decoded document (and its copy of `pieces`) around.
 fn torrent(args: &ArgMatches) {
     let file_path = args.get_one::<String>("FILE").expect("required");
-    println!("{}", decode(&fs::read(file_path).unwrap()));
-}
+    let data = fs::read(file_path).unwrap();
+
+    let mut decoder = Decoder::new(std::io::Cursor::new(data.as_slice()));
+    loop {
+	match decoder.next_element() {
+	    Some(Ok(element)) => println!("{}", element),
+	    Some(Err(e)) => { println!("{}", e); return; },
+	    None => break,
+	}
+    }
+
+    match downpour::find_dict_value_span(&data, b"info") {
+	Ok(Some((start, end))) => {
+	    let mut hasher = Sha1::new();
+	    hasher.update(&data[start..end]);
+	    let info_hash: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
 
-fn bencoding(_args: &ArgMatches) {
-    let mut buf: Vec<u8> = Vec::new();
-    let mut stdin: Stdin = std::io::stdin();
+	    let name = bytestring_at(&data[start..end], b"name").unwrap_or_default();
+	    let announce = bytestring_at(&data, b"announce").unwrap_or_default();
 
-    match stdin.read_to_end(&mut buf) {
-	Ok(_) => {
-	    println!("{}", decode(&buf));
+	    println!("info_hash: {}", info_hash);
+	    println!("magnet:?xt=urn:btih:{}&dn={}&tr={}", info_hash, urlencode(&name), urlencode(&announce));
 	},
+	Ok(None) => println!("No 'info' dict found: can't compute info_hash"),
+	Err(e) => println!("{}", e),
+    }
+}
+
+// looks up `key` in the top-level dict of `data` and decodes only that
+// value's byte span, instead of decoding the rest of `data` along with it
+fn bytestring_at(data: &[u8], key: &[u8]) -> Option<String> {
+    let (start, end) = downpour::find_dict_value_span(data, key).ok().flatten()?;
+
+    let element = match decode(&data[start..end]) {
+	DecodedDocument::Ok(elements) => elements.into_iter().next()?,
+	DecodedDocument::Err(_) => return None,
+    };
+
+    element.as_bytestring().map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+}
+
+// minimal percent-encoding for magnet link query values
+fn urlencode(s: &str) -> String {
+    let mut encoded = String::new();
+
+    for b in s.as_bytes() {
+	match b {
+	    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(*b as char),
+	    _ => encoded.push_str(&format!("%{:02X}", b)),
+	}
+    }
+
+    encoded
+}
+
+fn bencoding(args: &ArgMatches) {
+    let stdin = std::io::stdin();
+    let mut decoder = Decoder::new(stdin.lock());
+
+    if args.get_flag("encode") {
+	let mut elements: Vec<Element> = Vec::new();
+
+	loop {
+	    match decoder.next_element() {
+		Some(Ok(element)) => elements.push(element),
+		Some(Err(e)) => { println!("{}", e); return; },
+		None => break,
+	    }
+	}
 
-	_ => println!("Failed to read STDIN!"),
+	std::io::stdout().write_all(&encode(&elements)).unwrap();
+    } else {
+	loop {
+	    match decoder.next_element() {
+		Some(Ok(element)) => println!("{}", element),
+		Some(Err(e)) => { println!("{}", e); return; },
+		None => break,
+	    }
+	}
     }
-    
 }
 
 fn main() {
@@ -0,0 +1,516 @@
+//! A `serde::Serializer` that writes Rust values directly as bencode,
+//! mirroring the `Element`-based `encode` in `crate::encoder` but without
+//! needing callers to build an `Element` tree by hand.
+
+use serde::ser::{self, Error as SerError, Serialize};
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Message(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Error::Io(e) => write!(f, "{}", e),
+	    Error::Message(msg) => write!(f, "{}", msg),
+	}
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+	Error::Message(msg.to_string())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+	Error::Io(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Serializes `value` to a freshly allocated `Vec<u8>` of bencode.
+pub fn to_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut buf: Vec<u8> = Vec::new();
+    value.serialize(&mut Serializer::new(&mut buf))?;
+
+    Ok(buf)
+}
+
+fn to_buffer<T: ?Sized + Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut buf: Vec<u8> = Vec::new();
+    value.serialize(&mut Serializer::new(&mut buf))?;
+
+    Ok(buf)
+}
+
+/// Writes serde values directly as bencode onto a `Write`.
+pub struct Serializer<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Serializer<W> {
+    pub fn new(writer: W) -> Self {
+	Serializer { writer }
+    }
+}
+
+fn write_sorted_dict<W: Write>(ser: &mut Serializer<W>, mut entries: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    ser.writer.write_all(b"d")?;
+    for (key, value) in entries {
+	write!(ser.writer, "{}:", key.len())?;
+	ser.writer.write_all(&key)?;
+	ser.writer.write_all(&value)?;
+    }
+    ser.writer.write_all(b"e")?;
+
+    Ok(())
+}
+
+impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = SeqSerializer<'a, W>;
+    type SerializeTuple = SeqSerializer<'a, W>;
+    type SerializeTupleStruct = SeqSerializer<'a, W>;
+    type SerializeTupleVariant = SeqSerializer<'a, W>;
+    type SerializeMap = MapSerializer<'a, W>;
+    type SerializeStruct = MapSerializer<'a, W>;
+    type SerializeStructVariant = MapSerializer<'a, W>;
+
+    // bencode has no boolean type; represent it as the canonical 0/1 integer
+    fn serialize_bool(self, v: bool) -> Result<()> {
+	self.serialize_i64(if v { 1 } else { 0 })
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> { self.serialize_i64(v as i64) }
+    fn serialize_i16(self, v: i16) -> Result<()> { self.serialize_i64(v as i64) }
+    fn serialize_i32(self, v: i32) -> Result<()> { self.serialize_i64(v as i64) }
+    fn serialize_i64(self, v: i64) -> Result<()> {
+	write!(self.writer, "i{}e", v)?;
+	Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> { self.serialize_i64(v as i64) }
+    fn serialize_u16(self, v: u16) -> Result<()> { self.serialize_i64(v as i64) }
+    fn serialize_u32(self, v: u32) -> Result<()> { self.serialize_i64(v as i64) }
+    fn serialize_u64(self, v: u64) -> Result<()> {
+	match i64::try_from(v) {
+	    Ok(v) => self.serialize_i64(v),
+	    Err(_) => Err(Error::custom(format!("u64 value {} does not fit in a bencode integer", v))),
+	}
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+	Err(Error::custom(format!("bencode has no float type, can't serialize {}", v)))
+    }
+    fn serialize_f64(self, v: f64) -> Result<()> {
+	Err(Error::custom(format!("bencode has no float type, can't serialize {}", v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+	self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+	self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+	write!(self.writer, "{}:", v.len())?;
+	self.writer.write_all(v)?;
+	Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+	Err(Error::custom("bencode has no representation for a missing value"))
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
+	value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+	Err(Error::custom("bencode has no unit type"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+	self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str) -> Result<()> {
+	self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<()> {
+	value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+	self,
+	_name: &'static str,
+	_variant_index: u32,
+	variant: &'static str,
+	value: &T,
+    ) -> Result<()> {
+	write_sorted_dict(self, vec![(variant.as_bytes().to_vec(), to_buffer(value)?)])
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+	self.writer.write_all(b"l")?;
+	Ok(SeqSerializer{ ser: self })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+	self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct> {
+	self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+	self,
+	_name: &'static str,
+	_variant_index: u32,
+	variant: &'static str,
+	_len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+	// stream the wrapping `d<variant>l...e e` by hand since the list body
+	// is written incrementally but still needs the variant-name prefix
+	write!(self.writer, "d{}:{}l", variant.len(), variant)?;
+	Ok(SeqSerializer{ ser: self })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+	Ok(MapSerializer{ ser: self, entries: Vec::new(), next_key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+	Ok(MapSerializer{ ser: self, entries: Vec::new(), next_key: None })
+    }
+
+    fn serialize_struct_variant(
+	self,
+	_name: &'static str,
+	_variant_index: u32,
+	variant: &'static str,
+	_len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+	Ok(MapSerializer{ ser: self, entries: vec![(variant.as_bytes().to_vec(), Vec::new())], next_key: None })
+    }
+}
+
+pub struct SeqSerializer<'a, W: Write> {
+    ser: &'a mut Serializer<W>,
+}
+
+impl<'a, W: Write> ser::SerializeSeq for SeqSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+	value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+	self.ser.writer.write_all(b"e")?;
+	Ok(())
+    }
+}
+
+impl<'a, W: Write> ser::SerializeTuple for SeqSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+	ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<()> {
+	ser::SerializeSeq::end(self)
+    }
+}
+
+impl<'a, W: Write> ser::SerializeTupleStruct for SeqSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+	ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<()> {
+	ser::SerializeSeq::end(self)
+    }
+}
+
+impl<'a, W: Write> ser::SerializeTupleVariant for SeqSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+	ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<()> {
+	// close the inner list, then the wrapping dict opened in serialize_tuple_variant
+	self.ser.writer.write_all(b"ee")?;
+	Ok(())
+    }
+}
+
+pub struct MapSerializer<'a, W: Write> {
+    ser: &'a mut Serializer<W>,
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    next_key: Option<Vec<u8>>,
+}
+
+struct KeySerializer;
+
+macro_rules! key_serializer_unsupported {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+	$(
+	    fn $method(self, _v: $ty) -> Result<Vec<u8>> {
+		Err(Error::custom("bencode dict keys must be strings or byte slices"))
+	    }
+	)*
+    }
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = Vec<u8>;
+    type Error = Error;
+    type SerializeSeq = ser::Impossible<Vec<u8>, Error>;
+    type SerializeTuple = ser::Impossible<Vec<u8>, Error>;
+    type SerializeTupleStruct = ser::Impossible<Vec<u8>, Error>;
+    type SerializeTupleVariant = ser::Impossible<Vec<u8>, Error>;
+    type SerializeMap = ser::Impossible<Vec<u8>, Error>;
+    type SerializeStruct = ser::Impossible<Vec<u8>, Error>;
+    type SerializeStructVariant = ser::Impossible<Vec<u8>, Error>;
+
+    key_serializer_unsupported!(
+	serialize_bool(bool),
+	serialize_i8(i8), serialize_i16(i16), serialize_i32(i32), serialize_i64(i64),
+	serialize_u8(u8), serialize_u16(u16), serialize_u32(u32), serialize_u64(u64),
+	serialize_f32(f32), serialize_f64(f64),
+    );
+
+    fn serialize_char(self, v: char) -> Result<Vec<u8>> {
+	Ok(v.to_string().into_bytes())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Vec<u8>> {
+	Ok(v.as_bytes().to_vec())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Vec<u8>> {
+	Ok(v.to_vec())
+    }
+
+    fn serialize_none(self) -> Result<Vec<u8>> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Vec<u8>> {
+	value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Vec<u8>> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Vec<u8>> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str) -> Result<Vec<u8>> {
+	Ok(variant.as_bytes().to_vec())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<Vec<u8>> {
+	value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+	self, _name: &'static str, _variant_index: u32, _variant: &'static str, _value: &T,
+    ) -> Result<Vec<u8>> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+
+    fn serialize_tuple_variant(
+	self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+
+    fn serialize_struct_variant(
+	self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+	Err(Error::custom("bencode dict keys must be strings or byte slices"))
+    }
+}
+
+impl<'a, W: Write> ser::SerializeMap for MapSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
+	self.next_key = Some(key.serialize(KeySerializer)?);
+	Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+	let key = self.next_key.take().expect("serialize_value called before serialize_key");
+	self.entries.push((key, to_buffer(value)?));
+	Ok(())
+    }
+
+    fn end(self) -> Result<()> {
+	write_sorted_dict(self.ser, self.entries)
+    }
+}
+
+impl<'a, W: Write> ser::SerializeStruct for MapSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+	self.entries.push((key.as_bytes().to_vec(), to_buffer(value)?));
+	Ok(())
+    }
+
+    fn end(self) -> Result<()> {
+	write_sorted_dict(self.ser, self.entries)
+    }
+}
+
+impl<'a, W: Write> ser::SerializeStructVariant for MapSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+	self.entries.push((key.as_bytes().to_vec(), to_buffer(value)?));
+	Ok(())
+    }
+
+    fn end(mut self) -> Result<()> {
+	// the variant name was already queued as the struct's first entry in
+	// serialize_struct_variant, so its fields nest inside that one dict;
+	// but canonical sorting is per-dict, not across the outer/inner split,
+	// so wrap the field dict as the variant's value instead
+	let variant_entry = self.entries.remove(0);
+	let mut inner = Serializer::new(Vec::new());
+	write_sorted_dict(&mut inner, self.entries)?;
+	write_sorted_dict(self.ser, vec![(variant_entry.0, inner.writer)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Info {
+	name: String,
+	length: i64,
+    }
+
+    #[derive(Serialize)]
+    struct TorrentFile {
+	announce: String,
+	info: Info,
+    }
+
+    #[test]
+    fn serializes_struct_as_canonical_dict() {
+	let torrent = TorrentFile{
+	    announce: "http://bttracker.debian.org:6969/announce".to_string(),
+	    info: Info{ name: "debian.iso".to_string(), length: 12345 },
+	};
+
+	assert_eq!(
+	    to_bytes(&torrent).unwrap(),
+	    b"d8:announce41:http://bttracker.debian.org:6969/announce4:infod6:lengthi12345e4:name10:debian.isoee".to_vec()
+	);
+    }
+
+    #[test]
+    fn serializes_seq_and_integers() {
+	assert_eq!(to_bytes(&vec![1i64, 2i64, 3i64]).unwrap(), b"li1ei2ei3ee".to_vec());
+    }
+
+    #[test]
+    fn serializes_str_as_bytestring() {
+	assert_eq!(to_bytes(&"announce").unwrap(), b"8:announce".to_vec());
+    }
+
+    #[derive(Serialize)]
+    enum Message {
+	Ping,
+	Text(String),
+	Point(i64, i64),
+	Login{ name: String, id: i64 },
+    }
+
+    #[test]
+    fn serializes_unit_variant_as_bytestring() {
+	assert_eq!(to_bytes(&Message::Ping).unwrap(), b"4:Ping".to_vec());
+    }
+
+    #[test]
+    fn serializes_newtype_variant_as_single_entry_dict() {
+	assert_eq!(
+	    to_bytes(&Message::Text("hello".to_string())).unwrap(),
+	    b"d4:Text5:helloe".to_vec()
+	);
+    }
+
+    #[test]
+    fn serializes_tuple_variant_as_dict_of_list() {
+	assert_eq!(
+	    to_bytes(&Message::Point(1, 2)).unwrap(),
+	    b"d5:Pointli1ei2eee".to_vec()
+	);
+    }
+
+    #[test]
+    fn serializes_struct_variant_as_dict_of_dict() {
+	assert_eq!(
+	    to_bytes(&Message::Login{ name: "a".to_string(), id: 5 }).unwrap(),
+	    b"d5:Logind2:idi5e4:name1:aee".to_vec()
+	);
+    }
+}